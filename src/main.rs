@@ -1,3 +1,5 @@
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use noise::{NoiseFn, Perlin};
 use rand::Rng;
 use ratatui::{
@@ -7,7 +9,10 @@ use ratatui::{
     text::{Span},
     widgets::*,
 };
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::env;
 use std::io::{self, stdout};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
@@ -18,6 +23,12 @@ use std::time::Duration;
 
 const MAX_INVENTORY: u32 = 5;
 const MAX_LOGS: usize = 10;
+const MAP_WIDTH: usize = 150;
+const MAP_HEIGHT: usize = 50;
+const TICK_DELAY_STEP_MS: u64 = 20;
+const TICK_DELAY_MIN_MS: u64 = 20;
+const TICK_DELAY_MAX_MS: u64 = 1000;
+const SAVE_PATH: &str = "savegame.toml";
 
 fn log_event(logs: &Arc<Mutex<Vec<String>>>, msg: &str) {
     let mut logs = logs.lock().unwrap();
@@ -27,51 +38,144 @@ fn log_event(logs: &Arc<Mutex<Vec<String>>>, msg: &str) {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 enum RobotType {
     Explorer,
     Miner,
 }
 
+/// Région climatique d'une case, influençant obstacles et ressources.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum Biome {
+    Plains,
+    Rock,
+    Crystal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum Terrain {
+    Open,
+    Wall,
+    Base,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum ResourceKind {
+    Mineral,
+    Energy,
+}
+
+impl ResourceKind {
+    fn label(&self) -> &'static str {
+        match self {
+            ResourceKind::Mineral => "minerai",
+            ResourceKind::Energy => "énergie",
+        }
+    }
+}
+
+/// Case de la carte : biome, terrain, coût de déplacement et ressource.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Tile {
+    biome: Biome,
+    terrain: Terrain,
+    cost: usize,
+    resource: Option<ResourceKind>,
+}
+
+impl Default for Tile {
+    fn default() -> Self {
+        Self {
+            biome: Biome::Plains,
+            terrain: Terrain::Open,
+            cost: 1,
+            resource: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct Map {
     width: usize,
     height: usize,
-    data: Vec<Vec<char>>,
+    data: Vec<Vec<Tile>>,
     base_x: usize,
     base_y: usize,
 }
 
 impl Map {
     fn new(width: usize, height: usize, seed: u32) -> Self {
-        let perlin = Perlin::new(seed);
-        let mut data = vec![vec!['.'; width]; height];
+        let terrain_noise = Perlin::new(seed);
+        // Fréquence plus basse que le bruit de terrain, pour de grandes régions.
+        let biome_noise = Perlin::new(seed.wrapping_add(1));
+        let mut data = vec![vec![Tile::default(); width]; height];
         let mut rng = rand::thread_rng();
 
-        // Génération du terrain avec du bruit de Perlin
+        // Biome par case, puis seuil d'obstacle et coût qui en dépendent.
         for y in 0..height {
             for x in 0..width {
-                let noise_value = perlin.get([x as f64 / 10.0, y as f64 / 10.0]);
-                if noise_value > 0.4 {
-                    data[y][x] = '#';
-                }
+                let biome_value = biome_noise.get([x as f64 / 40.0, y as f64 / 40.0]);
+                let biome = if biome_value > 0.25 {
+                    Biome::Crystal
+                } else if biome_value < -0.25 {
+                    Biome::Rock
+                } else {
+                    Biome::Plains
+                };
+
+                let wall_threshold = match biome {
+                    Biome::Rock => 0.2,
+                    Biome::Crystal => 0.5,
+                    Biome::Plains => 0.4,
+                };
+                let noise_value = terrain_noise.get([x as f64 / 10.0, y as f64 / 10.0]);
+                let terrain = if noise_value > wall_threshold {
+                    Terrain::Wall
+                } else {
+                    Terrain::Open
+                };
+
+                let cost = match biome {
+                    Biome::Plains => 1,
+                    Biome::Crystal => 2,
+                    Biome::Rock => 3,
+                };
+
+                data[y][x] = Tile {
+                    biome,
+                    terrain,
+                    cost,
+                    resource: None,
+                };
             }
         }
 
         // Position de la base au centre de la carte
         let base_x = width / 2;
         let base_y = height / 2;
-        data[base_y][base_x] = 'S';
+        data[base_y][base_x].terrain = Terrain::Base;
 
-        // Placement aléatoire des ressources sur les cases vides
+        // Minerai en zone rocheuse, énergie en zone de cristal, rare en plaine.
         let mut resource_positions = Vec::new();
         let max_resources = (width * height) / 10;
         while resource_positions.len() < max_resources {
             let x = rng.gen_range(0..width);
             let y = rng.gen_range(0..height);
-            if data[y][x] == '.' {
+            let tile = data[y][x];
+            if tile.terrain != Terrain::Open || tile.resource.is_some() {
+                continue;
+            }
+            let resource = match tile.biome {
+                Biome::Rock => Some(ResourceKind::Mineral),
+                Biome::Crystal => Some(ResourceKind::Energy),
+                Biome::Plains if rng.gen_bool(0.3) => {
+                    Some(if rng.gen_bool(0.5) { ResourceKind::Mineral } else { ResourceKind::Energy })
+                }
+                Biome::Plains => None,
+            };
+            if let Some(resource) = resource {
                 resource_positions.push((x, y));
-                let resource_type = rng.gen_range(0..2);
-                data[y][x] = if resource_type == 0 { 'M' } else { 'E' };
+                data[y][x].resource = Some(resource);
             }
         }
 
@@ -84,26 +188,208 @@ impl Map {
         }
     }
 
-    pub fn clone_map(&self) -> Map {
-        Map {
-            width: self.width,
-            height: self.height,
-            data: self.data.clone(),
-            base_x: self.base_x,
-            base_y: self.base_y,
+    fn is_walkable(&self, pos: (usize, usize)) -> bool {
+        self.data[pos.1][pos.0].terrain != Terrain::Wall
+    }
+
+    /// Voisinage 4-connexe avec repli modulo (la carte boucle sur elle-même).
+    fn neighbors(&self, pos: (usize, usize)) -> [(usize, usize); 4] {
+        let (x, y) = pos;
+        let w = self.width as isize;
+        let h = self.height as isize;
+        let wrap = |v: isize, m: isize| ((v % m) + m) % m;
+        [
+            (wrap(x as isize - 1, w) as usize, y),
+            (wrap(x as isize + 1, w) as usize, y),
+            (x, wrap(y as isize - 1, h) as usize),
+            (x, wrap(y as isize + 1, h) as usize),
+        ]
+    }
+
+    /// Recherche de chemin A* jusqu'à `goal`, `g` pondéré par le coût de
+    /// chaque case (l'heuristique de Manhattan reste admissible tant
+    /// qu'aucun coût n'est inférieur à 1). Retourne la suite de cases à
+    /// traverser (sans la case de départ), ou `None` si aucun chemin n'existe.
+    fn find_path(&self, start: (usize, usize), goal: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+        if start == goal {
+            return Some(Vec::new());
+        }
+
+        let mut open_set = BinaryHeap::new();
+        let mut g_score: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut came_from: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+
+        g_score.insert(start, 0);
+        open_set.push(AStarNode {
+            f_score: manhattan(start, goal, self.width, self.height),
+            pos: start,
+        });
+
+        while let Some(AStarNode { pos: current, .. }) = open_set.pop() {
+            if current == goal {
+                let mut path = Vec::new();
+                let mut node = current;
+                while let Some(&prev) = came_from.get(&node) {
+                    path.push(node);
+                    node = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let current_g = g_score[&current];
+            for next in self.neighbors(current) {
+                if !self.is_walkable(next) {
+                    continue;
+                }
+                let tentative_g = current_g + self.data[next.1][next.0].cost;
+                if tentative_g < *g_score.get(&next).unwrap_or(&usize::MAX) {
+                    came_from.insert(next, current);
+                    g_score.insert(next, tentative_g);
+                    open_set.push(AStarNode {
+                        f_score: tentative_g + manhattan(next, goal, self.width, self.height),
+                        pos: next,
+                    });
+                }
+            }
         }
+
+        None
     }
 }
 
-#[derive(Clone)]
+/// Distance de Manhattan sur la carte torique : sur chaque axe, le plus
+/// court des deux sens.
+fn manhattan(a: (usize, usize), b: (usize, usize), width: usize, height: usize) -> usize {
+    let dx = a.0.abs_diff(b.0);
+    let dy = a.1.abs_diff(b.1);
+    dx.min(width - dx) + dy.min(height - dy)
+}
+
+/// Noeud de la file de priorité de l'A*, ordonné par `f = g + h` croissant.
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct AStarNode {
+    f_score: usize,
+    pos: (usize, usize),
+}
+
+impl Ord for AStarNode {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other.f_score.cmp(&self.f_score)
+    }
+}
+
+impl PartialOrd for AStarNode {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+const VISION_RADIUS: usize = 8;
+
+/// Transformations (xx, xy, yx, yy) des 8 octants vers le repère de la carte.
+const OCTANT_TRANSFORM: [[isize; 4]; 8] = [
+    [1, 0, 0, 1],
+    [0, 1, 1, 0],
+    [0, -1, 1, 0],
+    [-1, 0, 0, 1],
+    [-1, 0, 0, -1],
+    [0, -1, -1, 0],
+    [0, 1, -1, 0],
+    [1, 0, 0, -1],
+];
+
+fn wrap_pos(map: &Map, x: isize, y: isize) -> (usize, usize) {
+    let w = map.width as isize;
+    let h = map.height as isize;
+    ((((x % w) + w) % w) as usize, (((y % h) + h) % h) as usize)
+}
+
+/// Cases visibles depuis `origin` dans un rayon donné, par shadowcasting
+/// symétrique octant par octant.
+fn shadowcast_visible(map: &Map, origin: (usize, usize), radius: usize) -> HashSet<(usize, usize)> {
+    let mut visible = HashSet::new();
+    visible.insert(origin);
+    for octant in 0..8 {
+        cast_octant(map, origin, radius, octant, 1, 1.0, 0.0, &mut visible);
+    }
+    visible
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cast_octant(
+    map: &Map,
+    origin: (usize, usize),
+    radius: usize,
+    octant: usize,
+    row: usize,
+    start_slope: f64,
+    end_slope: f64,
+    visible: &mut HashSet<(usize, usize)>,
+) {
+    if start_slope < end_slope {
+        return;
+    }
+
+    let [xx, xy, yx, yy] = OCTANT_TRANSFORM[octant];
+    let radius_sq = (radius * radius) as f64;
+    let mut start_slope = start_slope;
+
+    for r in row..=radius {
+        let dy = -(r as isize);
+        let mut blocked = false;
+        let mut new_start = start_slope;
+
+        for dx in -(r as isize)..=0 {
+            let l_slope = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+            let r_slope = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+
+            if r_slope > start_slope {
+                continue;
+            }
+            if l_slope < end_slope {
+                break;
+            }
+
+            let map_x = origin.0 as isize + dx * xx + dy * yx;
+            let map_y = origin.1 as isize + dx * xy + dy * yy;
+            let cell = wrap_pos(map, map_x, map_y);
+
+            if (dx * dx + dy * dy) as f64 <= radius_sq {
+                visible.insert(cell);
+            }
+
+            let is_wall = map.data[cell.1][cell.0].terrain == Terrain::Wall;
+            if blocked {
+                if is_wall {
+                    new_start = r_slope;
+                    continue;
+                }
+                blocked = false;
+                start_slope = new_start;
+            } else if is_wall && r < radius {
+                blocked = true;
+                cast_octant(map, origin, radius, octant, r + 1, start_slope, l_slope, visible);
+                new_start = r_slope;
+            }
+        }
+
+        if blocked {
+            break;
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct Robot {
     id: usize,
     x: usize,
     y: usize,
     robot_type: RobotType,
-    inventory: u32,                
+    inventory: u32,
     target: Option<(usize, usize)>,
-    paused: bool,                  
+    path: Vec<(usize, usize)>,
+    paused: bool,
 }
 
 impl Robot {
@@ -115,97 +401,84 @@ impl Robot {
             robot_type,
             inventory: 0,
             target: None,
+            path: Vec::new(),
             paused: false,
         }
     }
 
-    /// Déplacement aléatoire en respectant les obstacles.
-    fn move_randomly(&mut self, width: usize, height: usize, map: &Map) {
+    /// Case visée par un déplacement aléatoire en respectant les obstacles.
+    fn planned_random_step(&self, width: usize, height: usize, map: &Map) -> (usize, usize) {
         let mut rng = rand::thread_rng();
         let directions = [(-1, 0), (1, 0), (0, -1), (0, 1)];
         let (dx, dy) = directions[rng.gen_range(0..directions.len())];
         let new_x = ((self.x as isize + dx) + width as isize) % width as isize;
         let new_y = ((self.y as isize + dy) + height as isize) % height as isize;
-        if map.data[new_y as usize][new_x as usize] != '#' {
-            self.x = new_x as usize;
-            self.y = new_y as usize;
+        let candidate = (new_x as usize, new_y as usize);
+        if map.is_walkable(candidate) {
+            candidate
+        } else {
+            (self.x, self.y)
         }
     }
 
-    /// Déplacement d'un pas vers la cible en évitant les obstacles.
-    fn move_towards(&mut self, target: (usize, usize), map: &Map) {
-        let (target_x, target_y) = target;
-        let mut new_x = self.x;
-        let mut new_y = self.y;
-        if self.x < target_x {
-            new_x += 1;
-        } else if self.x > target_x {
-            new_x -= 1;
-        }
-        if self.y < target_y {
-            new_y += 1;
-        } else if self.y > target_y {
-            new_y -= 1;
+    /// Case visée pour avancer vers `target` via un chemin A* mis en cache,
+    /// recalculé si la cible change ou si la prochaine case est bloquée.
+    fn planned_step_towards(&mut self, target: (usize, usize), map: &Map) -> (usize, usize) {
+        let needs_replan = self.path.is_empty()
+            || self.path.last() != Some(&target)
+            || !map.is_walkable(self.path[0]);
+
+        if needs_replan {
+            self.path = map
+                .find_path((self.x, self.y), target)
+                .unwrap_or_default();
         }
 
-        if map.data[new_y][new_x] != '#' {
-            self.x = new_x;
-            self.y = new_y;
-        } else {
-            self.move_randomly(map.width, map.height, map);
+        match self.path.first() {
+            Some(&next) if map.is_walkable(next) => next,
+            Some(_) => {
+                self.path.clear();
+                self.planned_random_step(map.width, map.height, map)
+            }
+            None => self.planned_random_step(map.width, map.height, map),
         }
     }
 
-    fn perform_task(
+    /// Décide de la case que ce robot souhaite occuper ce tick, sans toucher
+    /// à `map` ni aux autres robots (voir `resolve_conflicts`).
+    fn plan(
         &mut self,
-        map: &mut Map,
-        reported_resources: &Arc<Mutex<HashSet<(usize, usize)>>>,
+        map: &Map,
+        reported_resources: &mut HashSet<(usize, usize)>,
         logs: &Arc<Mutex<Vec<String>>>,
-    ) {
+    ) -> (usize, usize) {
         match self.robot_type {
-            RobotType::Explorer => {
-                let tile = map.data[self.y][self.x];
-                if tile == 'M' || tile == 'E' {
-                    {
-                        let mut rep = reported_resources.lock().unwrap();
-                        rep.insert((self.x, self.y));
-                    }
-                    log_event(
-                        logs,
-                        &format!("Ressource trouvée par l'explorateur en ({}, {})", self.x, self.y),
-                    );
-                }
-                self.move_randomly(map.width, map.height, map);
-            }
+            RobotType::Explorer => self.planned_random_step(map.width, map.height, map),
             RobotType::Miner => {
                 if self.inventory < MAX_INVENTORY {
                     // Pour le Miner id 2, rester en pause tant qu'il n'y a pas au moins 2 ressources et aucune cible n'est fixée.
-                    if self.id == 2 {
-                        let rep = reported_resources.lock().unwrap();
-                        if rep.len() < 2 && self.target.is_none() {
-                            // Décaler le robot de 1 pour ne pas masquer la base
-                            if self.x == map.base_x && self.y == map.base_y {
-                                if map.base_x + 1 < map.width {
-                                    self.x = map.base_x + 1;
-                                } else if map.base_x > 0 {
-                                    self.x = map.base_x - 1;
-                                }
-                            }
-                            if !self.paused {
-                                log_event(logs, "Robot 2 en pause (attente de 2 ressources)");
-                                self.paused = true;
+                    if self.id == 2 && reported_resources.len() < 2 && self.target.is_none() {
+                        if !self.paused {
+                            log_event(logs, "Robot 2 en pause (attente de 2 ressources)");
+                            self.paused = true;
+                        }
+                        // Décaler le robot de 1 pour ne pas masquer la base pendant la pause.
+                        if self.x == map.base_x && self.y == map.base_y {
+                            if map.base_x + 1 < map.width {
+                                return (map.base_x + 1, map.base_y);
+                            } else if map.base_x > 0 {
+                                return (map.base_x - 1, map.base_y);
                             }
-                            return;
-                        } else {
-                            self.paused = false;
                         }
+                        return (self.x, self.y);
                     }
+                    self.paused = false;
+
                     // S'il n'a pas de cible, en fixer une
                     if self.target.is_none() {
-                        let rep = reported_resources.lock().unwrap();
                         if self.id == 2 {
-                            if rep.len() >= 2 {
-                                if let Some(&target) = rep.iter().nth(1) {
+                            if reported_resources.len() >= 2 {
+                                if let Some(&target) = reported_resources.iter().nth(1) {
                                     self.target = Some(target);
                                     log_event(
                                         logs,
@@ -216,24 +489,75 @@ impl Robot {
                                     );
                                 }
                             }
-                        } else {
-                            if let Some(&target) = rep.iter().next() {
-                                self.target = Some(target);
-                                log_event(
-                                    logs,
-                                    &format!(
-                                        "Robot 1 se mobilise sur la ressource en ({}, {})",
-                                        target.0, target.1
-                                    ),
-                                );
-                            }
+                        } else if let Some(&target) = reported_resources.iter().next() {
+                            self.target = Some(target);
+                            log_event(
+                                logs,
+                                &format!(
+                                    "Robot {} se mobilise sur la ressource en ({}, {})",
+                                    self.id, target.0, target.1
+                                ),
+                            );
                         }
                     }
+
+                    if let Some(target) = self.target {
+                        self.planned_step_towards(target, map)
+                    } else {
+                        self.planned_random_step(map.width, map.height, map)
+                    }
+                } else {
+                    // Inventaire plein : retourner à la base pour vider
+                    self.planned_step_towards((map.base_x, map.base_y), map)
+                }
+            }
+        }
+    }
+
+    /// Applique la case choisie ce tick et dépile le chemin A* en conséquence.
+    fn apply_move(&mut self, pos: (usize, usize)) {
+        if pos != (self.x, self.y) {
+            if self.path.first() == Some(&pos) {
+                self.path.remove(0);
+            } else {
+                self.path.clear();
+            }
+            self.x = pos.0;
+            self.y = pos.1;
+        }
+    }
+
+    /// Effets de bord après déplacement : report de ressource, récolte ou
+    /// dépôt à la base ; les cases changées sont ajoutées à `changed`.
+    fn interact(
+        &mut self,
+        map: &mut Map,
+        reported_resources: &mut HashSet<(usize, usize)>,
+        logs: &Arc<Mutex<Vec<String>>>,
+        changed: &mut Vec<((usize, usize), Tile)>,
+    ) {
+        match self.robot_type {
+            RobotType::Explorer => {
+                if let Some(resource) = map.data[self.y][self.x].resource {
+                    reported_resources.insert((self.x, self.y));
+                    log_event(
+                        logs,
+                        &format!(
+                            "Ressource ({}) trouvée par l'explorateur en ({}, {})",
+                            resource.label(),
+                            self.x,
+                            self.y
+                        ),
+                    );
+                }
+            }
+            RobotType::Miner => {
+                if self.inventory < MAX_INVENTORY {
                     if let Some(target) = self.target {
-                        self.move_towards(target, map);
                         if self.x == target.0 && self.y == target.1 {
-                            if map.data[self.y][self.x] == 'M' || map.data[self.y][self.x] == 'E' {
-                                map.data[self.y][self.x] = '.';
+                            if map.data[self.y][self.x].resource.is_some() {
+                                map.data[self.y][self.x].resource = None;
+                                changed.push(((self.x, self.y), map.data[self.y][self.x]));
                                 self.inventory += 1;
                                 log_event(
                                     logs,
@@ -243,61 +567,314 @@ impl Robot {
                                     ),
                                 );
                             }
-                            let mut rep = reported_resources.lock().unwrap();
-                            rep.remove(&target);
+                            reported_resources.remove(&target);
                             self.target = None;
                         }
-                    } else {
-                        self.move_randomly(map.width, map.height, map);
-                    }
-                } else {
-                    // Inventaire plein : retourner à la base pour vider
-                    self.move_towards((map.base_x, map.base_y), map);
-                    if self.x == map.base_x && self.y == map.base_y {
-                        log_event(
-                            logs,
-                            &format!("Robot {} vient de se vider (inventaire: {})", self.id, self.inventory),
-                        );
-                        self.inventory = 0;
                     }
+                } else if self.x == map.base_x && self.y == map.base_y {
+                    log_event(
+                        logs,
+                        &format!("Robot {} vient de se vider (inventaire: {})", self.id, self.inventory),
+                    );
+                    self.inventory = 0;
                 }
             }
         }
     }
 }
 
-/// Affichage de la simulation et des logs dans le terminal.
+/// Diff de tick envoyé à l'UI : cases changées, position des robots, vitesse
+/// et pause ; la toute première émission contient la carte entière.
+struct MapUpdate {
+    changed: Vec<((usize, usize), Tile)>,
+    robots: Vec<Robot>,
+    tick_delay_ms: u64,
+    paused: bool,
+}
+
+/// Commande du clavier vers le planificateur, seul propriétaire de l'état.
+enum SchedulerCommand {
+    TogglePause,
+    SpeedUp,
+    SlowDown,
+    SpawnExplorer,
+    SpawnMiner,
+    Save,
+}
+
+/// Snapshot complet d'une simulation en cours.
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+    map: Map,
+    robots: Vec<Robot>,
+    reported_resources: HashSet<(usize, usize)>,
+    #[serde(with = "known_map_serde")]
+    known_map: Vec<Vec<Option<Tile>>>,
+    tick_delay_ms: u64,
+    next_robot_id: usize,
+}
+
+/// TOML n'a pas de valeur nulle dans un tableau : chaque case connue est
+/// écrite comme une liste vide (jamais vue) ou à un seul élément.
+mod known_map_serde {
+    use super::Tile;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(grid: &[Vec<Option<Tile>>], s: S) -> Result<S::Ok, S::Error> {
+        let rows: Vec<Vec<Vec<Tile>>> = grid.iter().map(|r| r.iter().map(|c| c.iter().copied().collect()).collect()).collect();
+        rows.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Vec<Option<Tile>>>, D::Error> {
+        let rows: Vec<Vec<Vec<Tile>>> = Vec::deserialize(d)?;
+        Ok(rows.into_iter().map(|r| r.into_iter().map(|c| c.into_iter().next()).collect()).collect())
+    }
+}
+
+fn save_state(path: &str, state: &SaveState) -> io::Result<()> {
+    let toml_str = toml::to_string_pretty(state).map_err(io::Error::other)?;
+    std::fs::write(path, toml_str)
+}
+
+fn load_state(path: &str) -> io::Result<SaveState> {
+    let contents = std::fs::read_to_string(path)?;
+    toml::from_str(&contents).map_err(io::Error::other)
+}
+
+/// Construit une nouvelle simulation vierge.
+fn fresh_world() -> SaveState {
+    let map = Map::new(MAP_WIDTH, MAP_HEIGHT, 42);
+    let base_x = map.base_x;
+    let base_y = map.base_y;
+
+    let robots = vec![
+        Robot::new(0, base_x, base_y, RobotType::Explorer),
+        Robot::new(1, base_x, base_y, RobotType::Miner),
+        Robot::new(2, base_x, base_y, RobotType::Miner),
+    ];
+
+    let mut known_map = vec![vec![None; MAP_WIDTH]; MAP_HEIGHT];
+    known_map[base_y][base_x] = Some(map.data[base_y][base_x]);
+
+    let next_robot_id = robots.len();
+    SaveState {
+        map,
+        robots,
+        reported_resources: HashSet::new(),
+        known_map,
+        tick_delay_ms: 100,
+        next_robot_id,
+    }
+}
+
+/// Résout les conflits de déplacement par point fixe : seul le plus petit id
+/// gagne une case visée, et une case occupée par un robot resté sur place
+/// bloque aussi les autres (on itère, sinon un robot refusé au tour d'avant
+/// peut encore se faire doubler dessus par un autre mouvement).
+fn resolve_conflicts(robots: &[Robot], planned: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let current: Vec<(usize, usize)> = robots.iter().map(|r| (r.x, r.y)).collect();
+    let mut resolved = planned.to_vec();
+
+    loop {
+        let mut winner: HashMap<(usize, usize), usize> = HashMap::new();
+        for (i, pos) in resolved.iter().enumerate() {
+            if *pos == current[i] {
+                continue;
+            }
+            winner
+                .entry(*pos)
+                .and_modify(|best| {
+                    if robots[i].id < robots[*best].id {
+                        *best = i;
+                    }
+                })
+                .or_insert(i);
+        }
+
+        // Cases occupées par un robot resté sur place à ce stade.
+        let staying: HashSet<(usize, usize)> = (0..robots.len())
+            .filter(|&i| resolved[i] == current[i])
+            .map(|i| current[i])
+            .collect();
+
+        let mut changed = false;
+        resolved = resolved
+            .iter()
+            .enumerate()
+            .map(|(i, pos)| {
+                if *pos == current[i] {
+                    return *pos;
+                }
+                if winner.get(pos) == Some(&i) && !staying.contains(pos) {
+                    *pos
+                } else {
+                    changed = true;
+                    current[i]
+                }
+            })
+            .collect();
+
+        if !changed {
+            return resolved;
+        }
+    }
+}
+
+/// Glyphe affiché pour une case.
+fn tile_glyph(tile: Tile) -> char {
+    match tile.terrain {
+        Terrain::Wall => '#',
+        Terrain::Base => 'S',
+        Terrain::Open => match tile.resource {
+            Some(ResourceKind::Mineral) => 'M',
+            Some(ResourceKind::Energy) => 'E',
+            None => '.',
+        },
+    }
+}
+
+/// Style associé à une case (le fond dépend du biome, l'avant-plan du terrain).
+fn tile_style(tile: Tile) -> Style {
+    let bg = match tile.biome {
+        Biome::Plains => Color::Reset,
+        Biome::Rock => Color::Rgb(40, 40, 40),
+        Biome::Crystal => Color::Rgb(20, 40, 60),
+    };
+    let style = Style::default().bg(bg);
+
+    match tile.terrain {
+        Terrain::Wall => style.fg(Color::DarkGray),
+        Terrain::Base => style.fg(Color::Blue).add_modifier(Modifier::BOLD),
+        Terrain::Open => match tile.resource {
+            Some(ResourceKind::Mineral) => style.fg(Color::Yellow),
+            Some(ResourceKind::Energy) => style.fg(Color::Cyan),
+            None => style.fg(Color::White),
+        },
+    }
+}
+
+/// Affiche la simulation et les logs ; `local_map` est tenue à jour à partir
+/// des diffs du planificateur. Lit aussi le clavier (pause, vitesse, spawn,
+/// défilement) et transmet les commandes via `tx_cmd`.
 fn render_ui(
-    rx: mpsc::Receiver<Map>,
-    robots: Arc<Mutex<Vec<Robot>>>,
+    rx: mpsc::Receiver<MapUpdate>,
+    tx_cmd: mpsc::Sender<SchedulerCommand>,
     running: Arc<AtomicBool>,
     logs: Arc<Mutex<Vec<String>>>,
+    known_map: Arc<Mutex<Vec<Vec<Option<Tile>>>>>,
+    base_x: usize,
+    base_y: usize,
 ) -> io::Result<()> {
+    enable_raw_mode()?;
     let stdout = stdout();
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     terminal.clear()?;
 
+    let mut local_map = Map {
+        width: MAP_WIDTH,
+        height: MAP_HEIGHT,
+        data: vec![vec![Tile::default(); MAP_WIDTH]; MAP_HEIGHT],
+        base_x,
+        base_y,
+    };
+    let mut robots: Vec<Robot> = Vec::new();
+    let mut tick_delay_ms: u64 = 100;
+    let mut paused = false;
+    let mut scroll_x: usize = 0;
+    let mut scroll_y: usize = 0;
+
     while running.load(Ordering::SeqCst) {
-        if let Ok(map) = rx.recv_timeout(Duration::from_millis(100)) {
-            let robots_guard = robots.lock().expect("Erreur lors du verrouillage des robots");
-
-            // Construire l'affichage de la carte
-            let mut sim_lines: Vec<Line> = Vec::with_capacity(map.height);
-            for y in 0..map.height {
-                let mut spans: Vec<Span> = Vec::with_capacity(map.width);
-                for x in 0..map.width {
-                    let mut ch = map.data[y][x];
-                    let mut style = match ch {
-                        '#' => Style::default().fg(Color::DarkGray),
-                        'S' => Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD),
-                        'M' | 'E' => Style::default().fg(Color::Yellow),
-                        '.' => Style::default().fg(Color::White),
-                        _ => Style::default(),
-                    };
-
-                    for robot in robots_guard.iter() {
+        if event::poll(Duration::from_millis(16))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    match key.code {
+                        KeyCode::Char(' ') => {
+                            let _ = tx_cmd.send(SchedulerCommand::TogglePause);
+                        }
+                        KeyCode::Char('+') => {
+                            let _ = tx_cmd.send(SchedulerCommand::SpeedUp);
+                        }
+                        KeyCode::Char('-') => {
+                            let _ = tx_cmd.send(SchedulerCommand::SlowDown);
+                        }
+                        KeyCode::Char('e') => {
+                            let _ = tx_cmd.send(SchedulerCommand::SpawnExplorer);
+                        }
+                        KeyCode::Char('m') => {
+                            let _ = tx_cmd.send(SchedulerCommand::SpawnMiner);
+                        }
+                        KeyCode::Char('s') => {
+                            let _ = tx_cmd.send(SchedulerCommand::Save);
+                        }
+                        KeyCode::Up => scroll_y = scroll_y.saturating_sub(1),
+                        KeyCode::Down => scroll_y = (scroll_y + 1).min(local_map.height.saturating_sub(1)),
+                        KeyCode::Left => scroll_x = scroll_x.saturating_sub(1),
+                        KeyCode::Right => scroll_x = (scroll_x + 1).min(local_map.width.saturating_sub(1)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        if let Ok(update) = rx.try_recv() {
+            for ((x, y), tile) in update.changed {
+                local_map.data[y][x] = tile;
+            }
+            robots = update.robots;
+            tick_delay_ms = update.tick_delay_ms;
+            paused = update.paused;
+        }
+
+        let known_guard = known_map.lock().expect("Erreur lors du verrouillage de la carte connue");
+
+        // Cases actuellement dans le champ de vision d'un explorateur.
+        let mut currently_visible = HashSet::new();
+        for robot in robots.iter() {
+            if robot.robot_type == RobotType::Explorer {
+                currently_visible.extend(shadowcast_visible(&local_map, (robot.x, robot.y), VISION_RADIUS));
+            }
+        }
+
+        // Division de l'écran en trois blocs : simulation, logs, barre de statut.
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Percentage(65),
+                    Constraint::Percentage(30),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
+            .split(terminal.size()?);
+
+        // Vue limitée au panneau de simulation ; les flèches la font défiler.
+        let view_w = (chunks[0].width.saturating_sub(2) as usize).min(local_map.width);
+        let view_h = (chunks[0].height.saturating_sub(2) as usize).min(local_map.height);
+        let scroll_x = scroll_x.min(local_map.width.saturating_sub(view_w));
+        let scroll_y = scroll_y.min(local_map.height.saturating_sub(view_h));
+
+        // Case visible -> rendu normal, connue mais hors champ de vision -> atténué, jamais vue -> vide.
+        let mut sim_lines: Vec<Line> = Vec::with_capacity(view_h);
+        for y in scroll_y..scroll_y + view_h {
+            let mut spans: Vec<Span> = Vec::with_capacity(view_w);
+            for x in scroll_x..scroll_x + view_w {
+                let visible_now = currently_visible.contains(&(x, y));
+                let known = known_guard[y][x];
+
+                let (mut ch, mut style) = if visible_now {
+                    let tile = local_map.data[y][x];
+                    (tile_glyph(tile), tile_style(tile))
+                } else if let Some(known_tile) = known {
+                    (tile_glyph(known_tile), tile_style(known_tile).add_modifier(Modifier::DIM))
+                } else {
+                    (' ', Style::default())
+                };
+
+                if visible_now {
+                    for robot in robots.iter() {
                         if robot.x == x && robot.y == y {
                             match robot.robot_type {
                                 RobotType::Explorer => {
@@ -312,38 +889,42 @@ fn render_ui(
                             break;
                         }
                     }
-                    spans.push(Span::styled(ch.to_string(), style));
                 }
-                sim_lines.push(Line::from(spans));
+                spans.push(Span::styled(ch.to_string(), style));
             }
+            sim_lines.push(Line::from(spans));
+        }
 
-            // Récupérer les derniers logs
-            let log_lines: Vec<Line> = {
-                let logs_lock = logs.lock().unwrap();
-                logs_lock
-                    .iter()
-                    .map(|l| Line::from(Span::raw(l.clone())))
-                    .collect()
-            };
+        // Récupérer les derniers logs
+        let log_lines: Vec<Line> = {
+            let logs_lock = logs.lock().unwrap();
+            logs_lock
+                .iter()
+                .map(|l| Line::from(Span::raw(l.clone())))
+                .collect()
+        };
 
-            // Division de l'écran en deux blocs : simulation et logs
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Percentage(70), Constraint::Percentage(30)].as_ref())
-                .split(terminal.size()?);
-
-            let sim_paragraph = Paragraph::new(sim_lines)
-                .block(Block::default().borders(Borders::ALL).title("Simulation"));
-            let log_paragraph = Paragraph::new(log_lines)
-                .block(Block::default().borders(Borders::ALL).title("Récapitulatif"));
-
-            terminal.draw(|frame| {
-                frame.render_widget(sim_paragraph, chunks[0]);
-                frame.render_widget(log_paragraph, chunks[1]);
-            })?;
-        }
+        let status = format!(
+            " {} | Vitesse : {} ms/tick | Robots : {} | [espace] pause  [+/-] vitesse  [e/m] spawn  [s] sauvegarde  [←↑↓→] défilement",
+            if paused { "EN PAUSE" } else { "EN COURS" },
+            tick_delay_ms,
+            robots.len(),
+        );
+
+        let sim_paragraph = Paragraph::new(sim_lines)
+            .block(Block::default().borders(Borders::ALL).title("Simulation"));
+        let log_paragraph = Paragraph::new(log_lines)
+            .block(Block::default().borders(Borders::ALL).title("Récapitulatif"));
+        let status_paragraph = Paragraph::new(Line::from(Span::raw(status)));
+
+        terminal.draw(|frame| {
+            frame.render_widget(sim_paragraph, chunks[0]);
+            frame.render_widget(log_paragraph, chunks[1]);
+            frame.render_widget(status_paragraph, chunks[2]);
+        })?;
     }
     terminal.clear()?;
+    disable_raw_mode()?;
     Ok(())
 }
 
@@ -355,77 +936,166 @@ fn main() -> io::Result<()> {
     })
     .expect("Erreur lors de la configuration du handler Ctrl-C");
 
-    // Initialisation de la carte et de la position de la base.
-    let initial_map = Map::new(150, 50, 42);
-    let base_x = initial_map.base_x;
-    let base_y = initial_map.base_y;
-    let map = Arc::new(Mutex::new(initial_map));
+    // Reprend une simulation sauvegardée si `--load <fichier>` est passé sur
+    // la ligne de commande, sinon génère un monde neuf depuis la graine.
+    let args: Vec<String> = env::args().collect();
+    let load_path = args
+        .iter()
+        .position(|a| a == "--load")
+        .and_then(|i| args.get(i + 1))
+        .cloned();
 
-    // Tous les robots démarrent depuis la base.
-    let robots: Vec<_> = vec![
-        Robot::new(0, base_x, base_y, RobotType::Explorer),
-        Robot::new(1, base_x, base_y, RobotType::Miner),
-        Robot::new(2, base_x, base_y, RobotType::Miner),
-    ];
-    let robots_shared = Arc::new(Mutex::new(robots));
+    let SaveState {
+        map,
+        robots,
+        reported_resources,
+        known_map: known_data,
+        tick_delay_ms,
+        next_robot_id,
+    } = match load_path {
+        Some(path) => load_state(&path).unwrap_or_else(|e| {
+            eprintln!("Impossible de charger {} : {} — nouvelle simulation.", path, e);
+            fresh_world()
+        }),
+        None => fresh_world(),
+    };
+    let base_x = map.base_x;
+    let base_y = map.base_y;
 
-    let reported_resources = Arc::new(Mutex::new(HashSet::new()));
     let logs = Arc::new(Mutex::new(Vec::new()));
+    let known_map = Arc::new(Mutex::new(known_data));
 
-    let (tx, rx) = mpsc::channel();
-    let map_shared = Arc::clone(&map);
-    let mut handles = vec![];
-
-    for i in 0..3 {
-        let map_shared = Arc::clone(&map_shared);
-        let tx_clone = tx.clone();
-        let robots_shared_clone = Arc::clone(&robots_shared);
-        let running_clone = Arc::clone(&running);
-        let reported_resources_clone = Arc::clone(&reported_resources);
-        let logs_clone = Arc::clone(&logs);
-
-        let handle = thread::spawn(move || {
-            while running_clone.load(Ordering::SeqCst) {
-                {
-                    let mut map = match map_shared.lock() {
-                        Ok(guard) => guard,
-                        Err(e) => {
-                            eprintln!("Erreur de verrouillage de la carte : {}", e);
-                            break;
-                        }
-                    };
-                    let mut robots = match robots_shared_clone.lock() {
-                        Ok(guard) => guard,
-                        Err(e) => {
-                            eprintln!("Erreur de verrouillage des robots : {}", e);
-                            break;
+    let (tx, rx) = mpsc::channel::<MapUpdate>();
+    let (tx_cmd, rx_cmd) = mpsc::channel::<SchedulerCommand>();
+
+    let running_sched = Arc::clone(&running);
+    let logs_sched = Arc::clone(&logs);
+    let known_map_sched = Arc::clone(&known_map);
+
+    // Planificateur central : un seul thread avance tous les robots par tick
+    // (ordre fixe, conflits résolus par id croissant), sans cloner la carte
+    // pour l'UI. Les tirages aléatoires ne dépendent pas de la graine du
+    // monde, donc un run n'est pas rejoué à l'identique d'une exécution à l'autre.
+    let scheduler_handle = thread::spawn(move || {
+        let mut map = map;
+        let mut robots = robots;
+        let mut reported_resources = reported_resources;
+        let mut next_robot_id = next_robot_id;
+        let mut tick_delay_ms = tick_delay_ms;
+        // Pause globale (barre d'espace), distincte de `Robot::paused` qui ne
+        // sert qu'à l'auto-pause du mineur 2.
+        let mut sim_paused = false;
+
+        // Cliché initial pour amorcer la copie locale de l'UI ; la ligne est
+        // clonée car `map.data` n'est pas `Copy` et ne peut pas être capturée
+        // directement par la closure `move` interne.
+        let initial_changed: Vec<_> = (0..map.height)
+            .flat_map(|y| {
+                let row = map.data[y].clone();
+                (0..map.width).map(move |x| ((x, y), row[x]))
+            })
+            .collect();
+        if tx
+            .send(MapUpdate {
+                changed: initial_changed,
+                robots: robots.clone(),
+                tick_delay_ms,
+                paused: sim_paused,
+            })
+            .is_err()
+        {
+            return;
+        }
+
+        while running_sched.load(Ordering::SeqCst) {
+            // Applique les commandes clavier accumulées depuis le dernier tick.
+            for command in rx_cmd.try_iter() {
+                match command {
+                    SchedulerCommand::TogglePause => sim_paused = !sim_paused,
+                    SchedulerCommand::SpeedUp => {
+                        tick_delay_ms = tick_delay_ms.saturating_sub(TICK_DELAY_STEP_MS).max(TICK_DELAY_MIN_MS);
+                    }
+                    SchedulerCommand::SlowDown => {
+                        tick_delay_ms = (tick_delay_ms + TICK_DELAY_STEP_MS).min(TICK_DELAY_MAX_MS);
+                    }
+                    SchedulerCommand::SpawnExplorer => {
+                        robots.push(Robot::new(next_robot_id, map.base_x, map.base_y, RobotType::Explorer));
+                        log_event(&logs_sched, &format!("Explorateur {} déployé depuis la base", next_robot_id));
+                        next_robot_id += 1;
+                    }
+                    SchedulerCommand::SpawnMiner => {
+                        robots.push(Robot::new(next_robot_id, map.base_x, map.base_y, RobotType::Miner));
+                        log_event(&logs_sched, &format!("Mineur {} déployé depuis la base", next_robot_id));
+                        next_robot_id += 1;
+                    }
+                    SchedulerCommand::Save => {
+                        let known_snapshot = known_map_sched.lock().unwrap().clone();
+                        let state = SaveState {
+                            map: map.clone(),
+                            robots: robots.clone(),
+                            reported_resources: reported_resources.clone(),
+                            known_map: known_snapshot,
+                            tick_delay_ms,
+                            next_robot_id,
+                        };
+                        match save_state(SAVE_PATH, &state) {
+                            Ok(()) => log_event(&logs_sched, &format!("Simulation sauvegardée dans {}", SAVE_PATH)),
+                            Err(e) => log_event(&logs_sched, &format!("Échec de la sauvegarde : {}", e)),
                         }
-                    };
+                    }
+                }
+            }
 
-                    let mut robot = robots[i].clone();
-                    robot.perform_task(&mut map, &reported_resources_clone, &logs_clone);
-                    robots[i] = robot.clone();
+            let mut changed = Vec::new();
+            if !sim_paused {
+                // Phase 1 : planification, sans effet sur la carte ni les autres robots.
+                let planned: Vec<(usize, usize)> = robots
+                    .iter_mut()
+                    .map(|robot| robot.plan(&map, &mut reported_resources, &logs_sched))
+                    .collect();
 
-                    if let Err(e) = tx_clone.send(map.clone_map()) {
-                        eprintln!("Erreur lors de l'envoi d'une mise à jour : {}", e);
+                // Phase 2 : résolution des conflits de déplacement.
+                let resolved = resolve_conflicts(&robots, &planned);
+
+                // Phase 3 : déplacement puis effets de bord, une seule mutation par robot.
+                for (i, pos) in resolved.into_iter().enumerate() {
+                    robots[i].apply_move(pos);
+                    robots[i].interact(&mut map, &mut reported_resources, &logs_sched, &mut changed);
+                }
+
+                // Révèle les cases visibles par chaque explorateur sur la carte connue.
+                let mut known = known_map_sched.lock().unwrap();
+                for robot in robots.iter().filter(|r| r.robot_type == RobotType::Explorer) {
+                    for (vx, vy) in shadowcast_visible(&map, (robot.x, robot.y), VISION_RADIUS) {
+                        known[vy][vx] = Some(map.data[vy][vx]);
                     }
                 }
-                thread::sleep(Duration::from_millis(100));
             }
-        });
-        handles.push(handle);
-    }
+
+            if tx
+                .send(MapUpdate {
+                    changed,
+                    robots: robots.clone(),
+                    tick_delay_ms,
+                    paused: sim_paused,
+                })
+                .is_err()
+            {
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(tick_delay_ms));
+        }
+    });
 
     let running_ui = Arc::clone(&running);
     let ui_handle = thread::spawn(move || {
-        if let Err(e) = render_ui(rx, robots_shared, running_ui, logs) {
+        if let Err(e) = render_ui(rx, tx_cmd, running_ui, logs, known_map, base_x, base_y) {
             eprintln!("Erreur dans l'UI : {}", e);
         }
     });
 
-    for handle in handles {
-        handle.join().expect("Le thread robot a paniqué");
-    }
+    scheduler_handle.join().expect("Le thread planificateur a paniqué");
     ui_handle.join().expect("Le thread UI a paniqué");
 
     Ok(())